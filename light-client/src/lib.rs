@@ -0,0 +1,8 @@
+//! Crate-root module declarations added by this series.
+//!
+//! The rest of this crate's module tree (`store`, `errors`, `types`, etc.)
+//! is declared elsewhere in the full crate root, which isn't part of this
+//! change set; this file only declares what this series itself introduced,
+//! so [`slasher`] is reachable instead of sitting outside the module tree.
+
+pub mod slasher;
@@ -0,0 +1,301 @@
+//! Persistent equivocation (double-sign) detection.
+//!
+//! `Slasher` indexes every signed vote it observes by `(validator_address,
+//! height, round)`. If a second vote for a slot that already has an entry
+//! carries a different block id, the two votes conflict and are reported as
+//! [`DuplicateVoteEvidence`] via an [`EvidenceReporter`]. Unlike the
+//! in-memory fork detection the light client already performs while
+//! bisecting, this index survives process restarts, so a validator that
+//! double-signs across two separate runs of the node is still caught.
+
+use serde::{Deserialize, Serialize};
+
+use tendermint::block::CommitSig;
+use tendermint::evidence::DuplicateVoteEvidence;
+use tendermint::vote::{SignedVote, Vote};
+use tendermint::{account, chain};
+
+use tendermint_light_client::evidence::EvidenceReporter;
+use tendermint_light_client::types::{Height, LightBlock, PeerId, PublicKey};
+
+use crate::errors::{Error, ErrorKind};
+use crate::store::sled::backend::KvBackend;
+use crate::store::sled::utils::{key_value, KeyValueDb};
+
+const VOTES_PREFIX: &str = "slasher/votes";
+
+/// The slot a vote occupies: a validator may cast at most one vote per
+/// `(height, round)`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct Slot {
+    validator_address: account::Id,
+    height: Height,
+    round: u64,
+}
+
+impl Slot {
+    fn of(vote: &Vote) -> Self {
+        Self {
+            validator_address: vote.validator_address,
+            height: vote.height,
+            round: vote.round,
+        }
+    }
+}
+
+/// Persists the first vote observed for every `(validator, height, round)`
+/// slot and reports conflicting later votes as duplicate-vote evidence.
+#[derive(Debug, Clone)]
+pub struct Slasher<B: KvBackend> {
+    votes: KeyValueDb<Slot, Vote, B::Tree>,
+}
+
+impl<B: KvBackend> Slasher<B> {
+    pub fn new(db: &B) -> Self {
+        Self {
+            votes: key_value(db, VOTES_PREFIX),
+        }
+    }
+
+    /// Indexes `vote` if its signature verifies against `pubkey`.
+    ///
+    /// Returns `Ok(Some(evidence))` when `vote` conflicts with a
+    /// previously-seen, already-verified vote for the same slot, in which
+    /// case `evidence` has already been handed to `reporter`. The
+    /// first-seen vote for a slot is never overwritten.
+    pub fn observe_vote(
+        &mut self,
+        vote: &Vote,
+        pubkey: &PublicKey,
+        chain_id: &chain::Id,
+        reporter: &impl EvidenceReporter,
+        peer: PeerId,
+    ) -> Result<Option<DuplicateVoteEvidence>, Error> {
+        let signed_vote = SignedVote::new(
+            vote.clone(),
+            chain_id.clone(),
+            vote.validator_address,
+            vote.validator_index,
+        );
+
+        if signed_vote.verify(pubkey).is_err() {
+            // Never index an unverified vote: an attacker could otherwise
+            // plant a bogus "first" vote to make a validator's real vote
+            // look like the conflicting second one.
+            return Ok(None);
+        }
+
+        let slot = Slot::of(vote);
+
+        let first_seen = self
+            .votes
+            .get(&slot)
+            .map_err(|e| ErrorKind::Store.context(e))?;
+
+        match first_seen {
+            None => {
+                self.votes
+                    .insert(&slot, vote)
+                    .map_err(|e| ErrorKind::Store.context(e))?;
+                Ok(None)
+            }
+            Some(first_vote) if first_vote.block_id != vote.block_id => {
+                let first_signed = SignedVote::new(
+                    first_vote.clone(),
+                    chain_id.clone(),
+                    first_vote.validator_address,
+                    first_vote.validator_index,
+                );
+
+                let evidence = DuplicateVoteEvidence::new(first_signed, signed_vote)
+                    .map_err(|e| ErrorKind::Evidence.context(e))?;
+
+                reporter
+                    .report(evidence.clone().into(), peer)
+                    .map_err(|e| ErrorKind::Io.context(e))?;
+
+                Ok(Some(evidence))
+            }
+            Some(_) => Ok(None),
+        }
+    }
+
+    /// Reconstructs every precommit vote carried by `light_block`'s commit
+    /// and feeds each one through [`Slasher::observe_vote`], so a validator
+    /// that equivocates across two blocks the light client verifies (even in
+    /// separate process runs) is still caught. Mirrors how
+    /// `SledStore::import_trusted_checkpoint` reconstructs votes from a
+    /// commit's signatures to check its voting power.
+    pub fn observe_commit(
+        &mut self,
+        light_block: &LightBlock,
+        reporter: &impl EvidenceReporter,
+        peer: PeerId,
+    ) -> Result<Vec<DuplicateVoteEvidence>, Error> {
+        let header = &light_block.signed_header.header;
+        let commit = &light_block.signed_header.commit;
+        let chain_id = &header.chain_id;
+
+        let mut evidence = Vec::new();
+
+        for (index, commit_sig) in commit.signatures.iter().enumerate() {
+            let (validator_address, signature, timestamp) = match commit_sig {
+                CommitSig::BlockIdFlagCommit {
+                    validator_address,
+                    signature,
+                    timestamp,
+                } => (*validator_address, signature.clone(), *timestamp),
+                _ => continue,
+            };
+
+            let validator = match light_block.validators.validator(validator_address) {
+                Some(validator) => validator,
+                None => continue,
+            };
+
+            let vote = Vote {
+                vote_type: tendermint::vote::Type::Precommit,
+                height: header.height,
+                round: commit.round,
+                block_id: Some(commit.block_id.clone()),
+                timestamp,
+                validator_address,
+                validator_index: index as u64,
+                signature,
+            };
+
+            if let Some(duplicate) =
+                self.observe_vote(&vote, &validator.pub_key, chain_id, reporter, peer)?
+            {
+                evidence.push(duplicate);
+            }
+        }
+
+        Ok(evidence)
+    }
+
+    /// Drops every indexed vote for a height strictly below `before`, so the
+    /// index does not grow without bound past the trusting-period horizon.
+    pub fn prune(&mut self, before: Height) -> Result<(), Error> {
+        let stale: Vec<Slot> = self
+            .votes
+            .entries()
+            .filter(|(slot, _)| slot.height < before)
+            .map(|(slot, _)| slot)
+            .collect();
+
+        for slot in stale {
+            self.votes
+                .remove(&slot)
+                .map_err(|e| ErrorKind::Store.context(e))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::sled::test_support::TempDir;
+    use std::convert::TryFrom;
+    use tendermint::evidence::Evidence;
+    use tendermint::Hash;
+    use tendermint_light_client::components::io::IoError;
+    use tendermint_testgen::{Generator, Header, Validator, Vote as TestgenVote};
+
+    /// Discards every reported evidence; the tests inspect `observe_vote`'s
+    /// return value directly instead.
+    struct NullReporter;
+
+    impl EvidenceReporter for NullReporter {
+        fn report(&self, _evidence: Evidence, _peer: PeerId) -> Result<Hash, IoError> {
+            Ok(Hash::None)
+        }
+    }
+
+    /// Builds a single-validator vote at `height`/`round`, signing over a
+    /// header that differs (and so hashes differently, giving a different
+    /// `block_id`) depending on `time`.
+    fn vote_at(height: u64, round: u64, time: &str) -> (Vote, PublicKey, chain::Id) {
+        let validator = Validator::new("slasher-test-validator");
+        let header = Header::new(&[validator.clone()])
+            .height(height)
+            .time(time.parse().unwrap());
+
+        let vote = TestgenVote::new(validator.clone(), header.clone())
+            .round(round)
+            .generate()
+            .unwrap();
+        let pubkey = validator.get_public_key().unwrap();
+        let chain_id = header.generate().unwrap().chain_id;
+
+        (vote, pubkey, chain_id)
+    }
+
+    #[test]
+    fn vote_with_a_bad_signature_is_not_indexed() {
+        let scratch = TempDir::new("slasher_unsigned");
+        let db = scratch.open_sled();
+        let mut slasher: Slasher<sled::Db> = Slasher::new(&db);
+
+        let (vote, _matching_pubkey, chain_id) = vote_at(1, 0, "2020-01-01T00:00:00Z");
+        let wrong_pubkey = Validator::new("some-other-validator")
+            .get_public_key()
+            .unwrap();
+
+        let evidence = slasher
+            .observe_vote(&vote, &wrong_pubkey, &chain_id, &NullReporter, PeerId::new([0; 20]))
+            .unwrap();
+
+        assert!(evidence.is_none());
+        assert!(slasher.votes.get(&Slot::of(&vote)).unwrap().is_none());
+    }
+
+    #[test]
+    fn first_seen_vote_is_never_overwritten() {
+        let scratch = TempDir::new("slasher_first_seen");
+        let db = scratch.open_sled();
+        let mut slasher: Slasher<sled::Db> = Slasher::new(&db);
+
+        let (first, pubkey, chain_id) = vote_at(1, 0, "2020-01-01T00:00:00Z");
+        let (second, _, _) = vote_at(1, 0, "2020-01-02T00:00:00Z");
+
+        let peer = PeerId::new([0; 20]);
+        let first_evidence = slasher
+            .observe_vote(&first, &pubkey, &chain_id, &NullReporter, peer)
+            .unwrap();
+        let second_evidence = slasher
+            .observe_vote(&second, &pubkey, &chain_id, &NullReporter, peer)
+            .unwrap();
+
+        assert!(first_evidence.is_none());
+        assert!(second_evidence.is_some());
+
+        let stored = slasher.votes.get(&Slot::of(&first)).unwrap().unwrap();
+        assert_eq!(stored.block_id, first.block_id);
+    }
+
+    #[test]
+    fn prune_drops_only_slots_below_the_horizon() {
+        let scratch = TempDir::new("slasher_prune");
+        let db = scratch.open_sled();
+        let mut slasher: Slasher<sled::Db> = Slasher::new(&db);
+
+        let (old_vote, old_pubkey, old_chain_id) = vote_at(1, 0, "2020-01-01T00:00:00Z");
+        let (new_vote, new_pubkey, new_chain_id) = vote_at(10, 0, "2020-01-02T00:00:00Z");
+
+        let peer = PeerId::new([0; 20]);
+        slasher
+            .observe_vote(&old_vote, &old_pubkey, &old_chain_id, &NullReporter, peer)
+            .unwrap();
+        slasher
+            .observe_vote(&new_vote, &new_pubkey, &new_chain_id, &NullReporter, peer)
+            .unwrap();
+
+        slasher.prune(Height::try_from(5u64).unwrap()).unwrap();
+
+        assert!(slasher.votes.get(&Slot::of(&old_vote)).unwrap().is_none());
+        assert!(slasher.votes.get(&Slot::of(&new_vote)).unwrap().is_some());
+    }
+}
@@ -1,10 +1,23 @@
+pub mod backend;
+pub mod redb;
+#[cfg(test)]
+pub(crate) mod test_support;
 pub mod utils;
 
+use std::collections::HashSet;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tendermint::block::CommitSig;
+use tendermint::vote::{SignedVote, Vote};
+
 use crate::{
-    store::sled::utils::*,
+    errors::{Error, ErrorKind},
+    store::sled::{backend::KvBackend, utils::*},
     types::{Height, LightBlock},
 };
 
+use self::redb::RedbBackend;
 use super::{LightStore, Status};
 use sled::Db;
 
@@ -13,26 +26,30 @@ const VERIFIED_PREFIX: &str = "light_store/verified";
 const TRUSTED_PREFIX: &str = "light_store/trusted";
 const FAILED_PREFIX: &str = "light_store/failed";
 
-/// Persistent store backed by an on-disk `sled` database.
+/// Persistent store, generic over the storage engine backing it.
+///
+/// Defaults to `sled`, but any engine implementing [`KvBackend`] (e.g.
+/// [`redb::RedbBackend`]) can be selected at construction time via
+/// [`SledStore::new`].
 #[derive(Debug, Clone)]
-pub struct SledStore {
-    unverified_db: KeyValueDb<Height, LightBlock>,
-    verified_db: KeyValueDb<Height, LightBlock>,
-    trusted_db: KeyValueDb<Height, LightBlock>,
-    failed_db: KeyValueDb<Height, LightBlock>,
+pub struct SledStore<B: KvBackend = Db> {
+    unverified_db: KeyValueDb<Height, LightBlock, B::Tree>,
+    verified_db: KeyValueDb<Height, LightBlock, B::Tree>,
+    trusted_db: KeyValueDb<Height, LightBlock, B::Tree>,
+    failed_db: KeyValueDb<Height, LightBlock, B::Tree>,
 }
 
-impl SledStore {
-    pub fn new(db: &Db) -> Self {
+impl<B: KvBackend> SledStore<B> {
+    pub fn new(db: &B) -> Self {
         Self {
-            unverified_db: key_value(&db, UNVERIFIED_PREFIX),
-            verified_db: key_value(&db, VERIFIED_PREFIX),
-            trusted_db: key_value(&db, TRUSTED_PREFIX),
-            failed_db: key_value(&db, FAILED_PREFIX),
+            unverified_db: key_value(db, UNVERIFIED_PREFIX),
+            verified_db: key_value(db, VERIFIED_PREFIX),
+            trusted_db: key_value(db, TRUSTED_PREFIX),
+            failed_db: key_value(db, FAILED_PREFIX),
         }
     }
 
-    fn db(&self, status: Status) -> &KeyValueDb<Height, LightBlock> {
+    fn db(&self, status: Status) -> &KeyValueDb<Height, LightBlock, B::Tree> {
         match status {
             Status::Unverified => &self.unverified_db,
             Status::Verified => &self.verified_db,
@@ -40,23 +57,52 @@ impl SledStore {
             Status::Failed => &self.failed_db,
         }
     }
+
+    /// Atomically moves `light_block` into the `status` tree, removing it
+    /// from every other status tree as part of the same backend
+    /// transaction (see [`KvTree::atomic_reassign`](backend::KvTree::atomic_reassign)).
+    ///
+    /// This is the fallible, atomic primitive behind [`LightStore::update`]:
+    /// it either fully succeeds or leaves every tree exactly as it was,
+    /// unlike a remove-from-others/insert-into-target sequence of
+    /// independent operations, which a crash or error can interrupt
+    /// partway through and leave a block in zero or two status trees.
+    pub fn try_update(&mut self, light_block: &LightBlock, status: Status) -> Result<(), Error> {
+        let height = light_block.height();
+        let key_bytes = serde_cbor::to_vec(&height).map_err(|e| ErrorKind::Store.context(e))?;
+        let value_bytes =
+            serde_cbor::to_vec(light_block).map_err(|e| ErrorKind::Store.context(e))?;
+
+        let trees = [
+            self.unverified_db.tree(),
+            self.verified_db.tree(),
+            self.trusted_db.tree(),
+            self.failed_db.tree(),
+        ];
+
+        let target_index = match status {
+            Status::Unverified => 0,
+            Status::Verified => 1,
+            Status::Trusted => 2,
+            Status::Failed => 3,
+        };
+
+        B::Tree::atomic_reassign(trees, target_index, &key_bytes, &value_bytes)
+    }
 }
 
-impl LightStore for SledStore {
+impl<B: KvBackend> LightStore for SledStore<B> {
     fn get(&self, height: Height, status: Status) -> Option<LightBlock> {
         self.db(status).get(&height).ok().flatten()
     }
 
     fn update(&mut self, light_block: &LightBlock, status: Status) {
-        let height = light_block.height();
-
-        for other in Status::iter() {
-            if status != *other {
-                self.db(*other).remove(&height).ok();
-            }
-        }
-
-        self.db(status).insert(&height, light_block).ok();
+        // `try_update` performs the remove-from-others/insert-into-target
+        // sequence as a single atomic transaction across all four status
+        // trees; `update` is `LightStore`'s infallible signature, so it
+        // discards the error the way the rest of this trait impl does, but
+        // it can no longer leave a block in zero or two status trees.
+        self.try_update(light_block, status).ok();
     }
 
     fn insert(&mut self, light_block: LightBlock, status: Status) {
@@ -79,3 +125,422 @@ impl LightStore for SledStore {
         Box::new(self.db(status).iter())
     }
 }
+
+impl<B: KvBackend> SledStore<B> {
+    /// Drops light blocks strictly below `before`, across every status
+    /// tree, to bound the store's growth on a long-running node.
+    ///
+    /// Two kinds of heights are always kept regardless of `before`:
+    /// - every `keep_every`-th height, so the store retains periodic
+    ///   checkpoints instead of a single cliff at `before`;
+    /// - any height in `keep_heights` (e.g. a height still referenced by an
+    ///   in-flight `verification_trace`) or the latest trusted height,
+    ///   since subjective initialization on the next restart depends on it.
+    pub fn prune(&mut self, before: Height, keep_every: u64, keep_heights: &HashSet<Height>) {
+        let latest_trusted = self.latest(Status::Trusted).map(|block| block.height());
+
+        for status in Status::iter() {
+            let db = self.db(*status);
+
+            // `entries_before` walks the tree's ordered `range` up to
+            // `before` instead of scanning every entry, so this stays cheap
+            // even once a long-running node has accumulated many heights
+            // past the retention window.
+            let stale: Vec<Height> = db
+                .entries_before(&before)
+                .filter_map(|(height, _)| {
+                    let is_checkpoint = keep_every > 0 && height.value() % keep_every == 0;
+                    let is_kept = Some(height) == latest_trusted
+                        || keep_heights.contains(&height)
+                        || is_checkpoint;
+
+                    (!is_kept).then(|| height)
+                })
+                .collect();
+
+            for height in stale {
+                db.remove(&height).ok();
+            }
+        }
+    }
+}
+
+/// A single self-contained trusted checkpoint, exported from a [`SledStore`]
+/// and portable to any other store for fast subjective initialization.
+///
+/// The checkpoint is "signed" in the sense that matters for trust: it
+/// carries the full [`LightBlock`], validator set included, whose header is
+/// already covered by the validators' commit signatures. Importing a
+/// checkpoint re-derives the validator set hash and checks it against the
+/// header before trusting anything in it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TrustedCheckpoint {
+    light_block: LightBlock,
+}
+
+impl<B: KvBackend> SledStore<B> {
+    /// Serializes the latest trusted light block into a single checkpoint
+    /// file at `path`, for a fresh node to import via
+    /// [`SledStore::import_trusted_checkpoint`] instead of walking headers
+    /// from genesis.
+    pub fn export_trusted_checkpoint(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let light_block = self
+            .latest(Status::Trusted)
+            .ok_or_else(|| ErrorKind::Store.context("no trusted block to export"))?;
+
+        let checkpoint = TrustedCheckpoint { light_block };
+        let bytes =
+            serde_cbor::to_vec(&checkpoint).map_err(|e| ErrorKind::Store.context(e))?;
+
+        std::fs::write(path, bytes).map_err(|e| ErrorKind::Store.context(e))?;
+
+        Ok(())
+    }
+
+    /// Imports a checkpoint written by [`SledStore::export_trusted_checkpoint`],
+    /// inserting its light block with [`Status::Trusted`].
+    ///
+    /// Rejects the checkpoint if its embedded validator set doesn't hash to
+    /// the value the header commits to, if its commit's block id isn't this
+    /// header's hash, if that validator set's signatures on the commit don't
+    /// carry at least +2/3 of its voting power, or if its height is below
+    /// the height already trusted by this store (which would roll the store
+    /// back to an earlier, less-trusted state). Both the validator set and
+    /// the commit in a checkpoint file come from whoever wrote it, so these
+    /// checks only establish that the checkpoint is internally consistent:
+    /// a checkpoint signed by a validator set its writer made up passes them.
+    pub fn import_trusted_checkpoint(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let bytes = std::fs::read(path).map_err(|e| ErrorKind::Store.context(e))?;
+        let checkpoint: TrustedCheckpoint =
+            serde_cbor::from_slice(&bytes).map_err(|e| ErrorKind::Store.context(e))?;
+
+        let light_block = checkpoint.light_block;
+
+        if light_block.validators.hash() != light_block.signed_header.header.validators_hash {
+            return Err(ErrorKind::Store
+                .context("checkpoint validator set does not match its header's validators_hash")
+                .into());
+        }
+
+        if light_block.signed_header.commit.block_id.hash
+            != light_block.signed_header.header.hash()
+        {
+            return Err(ErrorKind::Store
+                .context("checkpoint commit does not sign its header")
+                .into());
+        }
+
+        verify_commit_has_quorum(&light_block)?;
+
+        if let Some(current_trusted) = self.latest(Status::Trusted) {
+            if light_block.height() < current_trusted.height() {
+                return Err(ErrorKind::Store
+                    .context("checkpoint height is below the already-trusted height")
+                    .into());
+            }
+        }
+
+        self.insert(light_block, Status::Trusted);
+
+        Ok(())
+    }
+}
+
+/// Checks that `light_block`'s commit carries signatures from validators in
+/// `light_block.validators` representing at least +2/3 of its total voting
+/// power, the same threshold the light client's verifier requires of every
+/// other header it accepts.
+///
+/// This only checks the commit against the validator set embedded in the
+/// same light block; it says nothing about whether that validator set
+/// itself should be trusted, which is [`SledStore::import_trusted_checkpoint`]'s
+/// validators-hash check and, ultimately, the operator's responsibility for
+/// choosing a checkpoint source.
+fn verify_commit_has_quorum(light_block: &LightBlock) -> Result<(), Error> {
+    let header = &light_block.signed_header.header;
+    let commit = &light_block.signed_header.commit;
+    let validators = &light_block.validators;
+
+    let total_power: u64 = validators.validators().iter().map(|v| v.power()).sum();
+
+    let mut signed_power: u64 = 0;
+
+    for (index, commit_sig) in commit.signatures.iter().enumerate() {
+        let (validator_address, signature, timestamp) = match commit_sig {
+            CommitSig::BlockIdFlagCommit {
+                validator_address,
+                signature,
+                timestamp,
+            } => (*validator_address, signature.clone(), *timestamp),
+            _ => continue,
+        };
+
+        let validator = match validators.validator(validator_address) {
+            Some(validator) => validator,
+            None => continue,
+        };
+
+        let vote = Vote {
+            vote_type: tendermint::vote::Type::Precommit,
+            height: header.height,
+            round: commit.round,
+            block_id: Some(commit.block_id.clone()),
+            timestamp,
+            validator_address,
+            validator_index: index as u64,
+            signature,
+        };
+
+        let signed_vote = SignedVote::new(
+            vote,
+            header.chain_id.clone(),
+            validator_address,
+            index as u64,
+        );
+
+        if signed_vote.verify(&validator.pub_key).is_ok() {
+            signed_power += validator.power();
+        }
+    }
+
+    if signed_power * 3 > total_power * 2 {
+        Ok(())
+    } else {
+        Err(ErrorKind::Store
+            .context("checkpoint commit does not carry +2/3 of its validator set's voting power")
+            .into())
+    }
+}
+
+/// A [`LightStore`] backed by either `sled` or [`redb::RedbBackend`], chosen
+/// at runtime (e.g. from an operator-facing CLI flag).
+///
+/// `SledStore::prune`, `try_update`, `export_trusted_checkpoint` and
+/// `import_trusted_checkpoint` are inherent methods, not part of the
+/// [`LightStore`] trait, so a caller that needs them (pruning, checkpoint
+/// import/export) can't hold a runtime-selected backend behind
+/// `Box<dyn LightStore>` alone. This enum is the concrete type that closes
+/// that gap: it implements `LightStore` by delegation and forwards the
+/// extra inherent methods too.
+#[derive(Debug, Clone)]
+pub enum AnyLightStore {
+    Sled(SledStore<Db>),
+    Redb(SledStore<RedbBackend>),
+}
+
+impl AnyLightStore {
+    /// Opens a `sled`-backed store at `path`.
+    pub fn open_sled(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let db = sled::open(path).map_err(|e| ErrorKind::Store.context(e))?;
+        Ok(AnyLightStore::Sled(SledStore::new(&db)))
+    }
+
+    /// Opens a `redb`-backed store at `path`.
+    pub fn open_redb(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let db = RedbBackend::open(path)?;
+        Ok(AnyLightStore::Redb(SledStore::new(&db)))
+    }
+
+    pub fn prune(&mut self, before: Height, keep_every: u64, keep_heights: &HashSet<Height>) {
+        match self {
+            AnyLightStore::Sled(store) => store.prune(before, keep_every, keep_heights),
+            AnyLightStore::Redb(store) => store.prune(before, keep_every, keep_heights),
+        }
+    }
+
+    pub fn try_update(&mut self, light_block: &LightBlock, status: Status) -> Result<(), Error> {
+        match self {
+            AnyLightStore::Sled(store) => store.try_update(light_block, status),
+            AnyLightStore::Redb(store) => store.try_update(light_block, status),
+        }
+    }
+
+    pub fn export_trusted_checkpoint(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        match self {
+            AnyLightStore::Sled(store) => store.export_trusted_checkpoint(path),
+            AnyLightStore::Redb(store) => store.export_trusted_checkpoint(path),
+        }
+    }
+
+    pub fn import_trusted_checkpoint(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        match self {
+            AnyLightStore::Sled(store) => store.import_trusted_checkpoint(path),
+            AnyLightStore::Redb(store) => store.import_trusted_checkpoint(path),
+        }
+    }
+}
+
+impl LightStore for AnyLightStore {
+    fn get(&self, height: Height, status: Status) -> Option<LightBlock> {
+        match self {
+            AnyLightStore::Sled(store) => store.get(height, status),
+            AnyLightStore::Redb(store) => store.get(height, status),
+        }
+    }
+
+    fn update(&mut self, light_block: &LightBlock, status: Status) {
+        match self {
+            AnyLightStore::Sled(store) => store.update(light_block, status),
+            AnyLightStore::Redb(store) => store.update(light_block, status),
+        }
+    }
+
+    fn insert(&mut self, light_block: LightBlock, status: Status) {
+        match self {
+            AnyLightStore::Sled(store) => store.insert(light_block, status),
+            AnyLightStore::Redb(store) => store.insert(light_block, status),
+        }
+    }
+
+    fn remove(&mut self, height: Height, status: Status) {
+        match self {
+            AnyLightStore::Sled(store) => store.remove(height, status),
+            AnyLightStore::Redb(store) => store.remove(height, status),
+        }
+    }
+
+    fn latest(&self, status: Status) -> Option<LightBlock> {
+        match self {
+            AnyLightStore::Sled(store) => store.latest(status),
+            AnyLightStore::Redb(store) => store.latest(status),
+        }
+    }
+
+    fn all(&self, status: Status) -> Box<dyn Iterator<Item = LightBlock>> {
+        match self {
+            AnyLightStore::Sled(store) => store.all(status),
+            AnyLightStore::Redb(store) => store.all(status),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_support::TempDir;
+    use super::*;
+
+    fn light_block_at(height: u64) -> LightBlock {
+        tendermint_testgen::LightBlock::new_default(height)
+            .generate()
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn latest_returns_the_highest_height() {
+        let scratch = TempDir::new("latest");
+        let db = scratch.open_sled();
+        let mut store = SledStore::new(&db);
+
+        for height in [1, 589_473_798_493, 12_342_425, 4] {
+            store.insert(light_block_at(height), Status::Verified);
+        }
+
+        assert_eq!(
+            store.latest(Status::Verified).map(|b| b.height().value()),
+            Some(589_473_798_493)
+        );
+    }
+
+    #[test]
+    fn update_moves_the_block_to_the_target_status_tree() {
+        let scratch = TempDir::new("update");
+        let db = scratch.open_sled();
+        let mut store = SledStore::new(&db);
+        let light_block = light_block_at(1);
+
+        store.insert(light_block.clone(), Status::Unverified);
+        store.update(&light_block, Status::Trusted);
+
+        for status in [Status::Unverified, Status::Verified, Status::Failed] {
+            assert!(store.get(light_block.height(), status).is_none());
+        }
+        assert!(store.get(light_block.height(), Status::Trusted).is_some());
+    }
+
+    #[test]
+    fn try_update_leaves_exactly_one_tree_holding_the_block() {
+        let scratch = TempDir::new("try_update");
+        let db = scratch.open_sled();
+        let mut store = SledStore::new(&db);
+        let light_block = light_block_at(1);
+
+        for status in &[
+            Status::Unverified,
+            Status::Verified,
+            Status::Trusted,
+            Status::Failed,
+            Status::Verified,
+        ] {
+            store.try_update(&light_block, *status).unwrap();
+
+            let holding: Vec<Status> = Status::iter()
+                .copied()
+                .filter(|s| store.get(light_block.height(), *s).is_some())
+                .collect();
+
+            assert_eq!(holding, vec![*status]);
+        }
+    }
+
+    #[test]
+    fn import_trusted_checkpoint_round_trips_a_valid_checkpoint() {
+        let source = TempDir::new("checkpoint_source");
+        let mut store = SledStore::new(&source.open_sled());
+        let light_block = light_block_at(5);
+        store.insert(light_block.clone(), Status::Trusted);
+
+        let path = source.path().join("checkpoint.cbor");
+        store.export_trusted_checkpoint(&path).unwrap();
+
+        let target = TempDir::new("checkpoint_target");
+        let mut fresh: SledStore<Db> = SledStore::new(&target.open_sled());
+        fresh.import_trusted_checkpoint(&path).unwrap();
+
+        assert_eq!(
+            fresh.latest(Status::Trusted).map(|b| b.height()),
+            Some(light_block.height())
+        );
+    }
+
+    #[test]
+    fn import_trusted_checkpoint_rejects_a_commit_without_quorum() {
+        let source = TempDir::new("checkpoint_no_quorum_source");
+        let mut light_block = light_block_at(5);
+
+        // Strip every signature so the commit carries zero voting power,
+        // even though the validator set still hashes to what the header
+        // expects.
+        for signature in light_block.signed_header.commit.signatures.iter_mut() {
+            *signature = CommitSig::BlockIdFlagAbsent;
+        }
+
+        let checkpoint = TrustedCheckpoint { light_block };
+        let bytes = serde_cbor::to_vec(&checkpoint).unwrap();
+        let path = source.path().join("checkpoint.cbor");
+        std::fs::write(&path, bytes).unwrap();
+
+        let mut store: SledStore<Db> = SledStore::new(&source.open_sled());
+        assert!(store.import_trusted_checkpoint(&path).is_err());
+    }
+
+    #[test]
+    fn any_light_store_delegates_to_whichever_backend_it_was_opened_with() {
+        let scratch = TempDir::new("any_light_store");
+        let light_block = light_block_at(1);
+
+        let mut sled_store = AnyLightStore::open_sled(scratch.path().join("sled")).unwrap();
+        sled_store.insert(light_block.clone(), Status::Trusted);
+        assert_eq!(
+            sled_store.latest(Status::Trusted).map(|b| b.height()),
+            Some(light_block.height())
+        );
+
+        let mut redb_store = AnyLightStore::open_redb(scratch.path().join("redb.db")).unwrap();
+        redb_store.insert(light_block.clone(), Status::Trusted);
+        assert_eq!(
+            redb_store.latest(Status::Trusted).map(|b| b.height()),
+            Some(light_block.height())
+        );
+    }
+}
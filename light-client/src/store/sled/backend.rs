@@ -0,0 +1,129 @@
+//! Pluggable storage-engine abstraction for the persistent light store.
+//!
+//! [`KeyValueDb`](super::utils::KeyValueDb) only needs a handful of raw
+//! byte-level operations to do its CBOR (de)serialization on top of; the
+//! [`KvBackend`]/[`KvTree`] traits capture exactly that surface, so the
+//! serialization layer can sit on top of any engine that implements them
+//! instead of being hardwired to `sled`. See [`super::redb`] for a second
+//! backend built on this abstraction.
+
+use crate::errors::{Error, ErrorKind};
+
+/// A storage engine capable of opening named, independently addressable
+/// trees (a.k.a. tables/column-families, depending on the engine).
+pub trait KvBackend: Clone + std::fmt::Debug {
+    /// A single named collection of key/value pairs within this backend.
+    type Tree: KvTree;
+
+    /// Open (creating if necessary) the tree with the given name.
+    fn open_tree(&self, name: impl Into<Vec<u8>>) -> Result<Self::Tree, Error>;
+}
+
+/// A single named collection of raw byte key/value pairs.
+///
+/// `KeyValueDb` handles all (de)serialization on top of this trait; it and
+/// [`SledStore::try_update`](super::SledStore::try_update), which calls
+/// [`KvTree::atomic_reassign`] directly, only ever pass already
+/// CBOR-encoded keys and values.
+pub trait KvTree: Clone + std::fmt::Debug {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), Error>;
+    fn remove(&self, key: &[u8]) -> Result<(), Error>;
+    fn contains_key(&self, key: &[u8]) -> Result<bool, Error>;
+
+    /// Iterate over all entries in ascending key order.
+    fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = (Vec<u8>, Vec<u8>)>>;
+
+    /// Iterate over the entries whose key falls within `start..end`
+    /// (ascending key order), where `start`/`end` are already CBOR-encoded
+    /// keys.
+    fn range(&self, start: Vec<u8>, end: Vec<u8>) -> Box<dyn DoubleEndedIterator<Item = (Vec<u8>, Vec<u8>)>>;
+
+    /// Atomically remove `key` from every tree in `trees` other than the one
+    /// at `target_index`, and insert `(key, value)` into that one tree, as a
+    /// single all-or-nothing operation across all four trees.
+    ///
+    /// This is what lets [`SledStore::try_update`](super::SledStore::try_update)
+    /// move a light block between status trees without a window where it is
+    /// present in zero or two of them.
+    fn atomic_reassign(
+        trees: [&Self; 4],
+        target_index: usize,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error>;
+}
+
+impl KvBackend for sled::Db {
+    type Tree = sled::Tree;
+
+    fn open_tree(&self, name: impl Into<Vec<u8>>) -> Result<Self::Tree, Error> {
+        sled::Db::open_tree(self, name.into()).map_err(|e| ErrorKind::Store.context(e).into())
+    }
+}
+
+impl KvTree for sled::Tree {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        sled::Tree::get(self, key)
+            .map(|maybe| maybe.map(|ivec| ivec.to_vec()))
+            .map_err(|e| ErrorKind::Store.context(e).into())
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        sled::Tree::insert(self, key, value)
+            .map(|_| ())
+            .map_err(|e| ErrorKind::Store.context(e).into())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<(), Error> {
+        sled::Tree::remove(self, key)
+            .map(|_| ())
+            .map_err(|e| ErrorKind::Store.context(e).into())
+    }
+
+    fn contains_key(&self, key: &[u8]) -> Result<bool, Error> {
+        sled::Tree::contains_key(self, key).map_err(|e| ErrorKind::Store.context(e).into())
+    }
+
+    fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = (Vec<u8>, Vec<u8>)>> {
+        Box::new(
+            sled::Tree::iter(self)
+                .flatten()
+                .map(|(k, v)| (k.to_vec(), v.to_vec())),
+        )
+    }
+
+    fn range(&self, start: Vec<u8>, end: Vec<u8>) -> Box<dyn DoubleEndedIterator<Item = (Vec<u8>, Vec<u8>)>> {
+        Box::new(
+            sled::Tree::range(self, start..end)
+                .flatten()
+                .map(|(k, v)| (k.to_vec(), v.to_vec())),
+        )
+    }
+
+    fn atomic_reassign(
+        trees: [&Self; 4],
+        target_index: usize,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error> {
+        use sled::transaction::Transactional;
+
+        let key = key.to_vec();
+        let value = value.to_vec();
+
+        (trees[0], trees[1], trees[2], trees[3])
+            .transaction(move |(t0, t1, t2, t3)| {
+                for (index, tree) in [t0, t1, t2, t3].into_iter().enumerate() {
+                    if index == target_index {
+                        tree.insert(key.clone(), value.clone())?;
+                    } else {
+                        tree.remove(key.clone())?;
+                    }
+                }
+
+                Ok(())
+            })
+            .map_err(|e: sled::transaction::TransactionError<()>| ErrorKind::Store.context(e).into())
+    }
+}
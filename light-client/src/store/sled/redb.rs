@@ -0,0 +1,166 @@
+//! [`redb`](https://docs.rs/redb) implementation of the [`KvBackend`] trait.
+//!
+//! `redb` is an embedded, single-file, ACID key/value store with bounded
+//! memory use and well-understood crash semantics, making it a reasonable
+//! alternative to `sled` for operators who want those properties. Tables in
+//! `redb` are identified by a `'static` name, so `open_tree` leaks the
+//! (small, bounded-cardinality) tree name once per process to obtain one.
+
+use std::sync::{Arc, Mutex};
+
+use redb::{Database, ReadableTable, TableDefinition};
+
+use super::backend::{KvBackend, KvTree};
+use crate::errors::{Error, ErrorKind};
+
+/// Storage engine backed by a single `redb` database file.
+#[derive(Clone, Debug)]
+pub struct RedbBackend {
+    db: Arc<Database>,
+}
+
+impl RedbBackend {
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        let db = Database::create(path).map_err(|e| ErrorKind::Store.context(e))?;
+        Ok(Self { db: Arc::new(db) })
+    }
+}
+
+impl KvBackend for RedbBackend {
+    type Tree = RedbTree;
+
+    fn open_tree(&self, name: impl Into<Vec<u8>>) -> Result<Self::Tree, Error> {
+        let name = String::from_utf8(name.into()).map_err(|e| ErrorKind::Store.context(e))?;
+        // Table names must be `'static` for `redb`; tree names are a small,
+        // fixed set known up front (one per light-store status), so leaking
+        // them once per process is bounded and acceptable.
+        static LEAKED_NAMES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+        let mut leaked = LEAKED_NAMES.lock().unwrap_or_else(|e| e.into_inner());
+        let name: &'static str = match leaked.iter().copied().find(|leaked| *leaked == name) {
+            Some(existing) => existing,
+            None => {
+                let fresh: &'static str = Box::leak(name.into_boxed_str());
+                leaked.push(fresh);
+                fresh
+            }
+        };
+
+        Ok(RedbTree {
+            db: self.db.clone(),
+            table: TableDefinition::new(name),
+        })
+    }
+}
+
+/// A single named table within a [`RedbBackend`].
+#[derive(Clone, Debug)]
+pub struct RedbTree {
+    db: Arc<Database>,
+    table: TableDefinition<'static, &'static [u8], &'static [u8]>,
+}
+
+impl KvTree for RedbTree {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let txn = self.db.begin_read().map_err(|e| ErrorKind::Store.context(e))?;
+        let table = match txn.open_table(self.table) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(ErrorKind::Store.context(e).into()),
+        };
+        let value = table
+            .get(key)
+            .map_err(|e| ErrorKind::Store.context(e))?
+            .map(|v| v.value().to_vec());
+        Ok(value)
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let txn = self.db.begin_write().map_err(|e| ErrorKind::Store.context(e))?;
+        {
+            let mut table = txn.open_table(self.table).map_err(|e| ErrorKind::Store.context(e))?;
+            table.insert(key, value).map_err(|e| ErrorKind::Store.context(e))?;
+        }
+        txn.commit().map_err(|e| ErrorKind::Store.context(e))?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<(), Error> {
+        let txn = self.db.begin_write().map_err(|e| ErrorKind::Store.context(e))?;
+        {
+            let mut table = txn.open_table(self.table).map_err(|e| ErrorKind::Store.context(e))?;
+            table.remove(key).map_err(|e| ErrorKind::Store.context(e))?;
+        }
+        txn.commit().map_err(|e| ErrorKind::Store.context(e))?;
+        Ok(())
+    }
+
+    fn contains_key(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = (Vec<u8>, Vec<u8>)>> {
+        let entries = self.collect_range(..);
+        Box::new(entries.into_iter())
+    }
+
+    fn range(&self, start: Vec<u8>, end: Vec<u8>) -> Box<dyn DoubleEndedIterator<Item = (Vec<u8>, Vec<u8>)>> {
+        let entries = self.collect_range(start.as_slice()..end.as_slice());
+        Box::new(entries.into_iter())
+    }
+
+    fn atomic_reassign(
+        trees: [&Self; 4],
+        target_index: usize,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error> {
+        // All four trees were opened from the same `RedbBackend`, so they
+        // share one `Database`; a single write transaction spanning every
+        // table they name is enough to make the reassignment atomic.
+        let txn = trees[0]
+            .db
+            .begin_write()
+            .map_err(|e| ErrorKind::Store.context(e))?;
+
+        for (index, tree) in trees.iter().enumerate() {
+            let mut table = txn
+                .open_table(tree.table)
+                .map_err(|e| ErrorKind::Store.context(e))?;
+
+            if index == target_index {
+                table
+                    .insert(key, value)
+                    .map_err(|e| ErrorKind::Store.context(e))?;
+            } else {
+                table.remove(key).map_err(|e| ErrorKind::Store.context(e))?;
+            }
+        }
+
+        txn.commit().map_err(|e| ErrorKind::Store.context(e))?;
+
+        Ok(())
+    }
+}
+
+impl RedbTree {
+    /// Materializes a range into a `Vec` up front: `redb`'s range iterator
+    /// borrows from the read transaction, which doesn't outlive this
+    /// function, so we can't return it lazily like `sled::Tree::range` does.
+    fn collect_range(&self, range: impl std::ops::RangeBounds<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let txn = match self.db.begin_read() {
+            Ok(txn) => txn,
+            Err(_) => return Vec::new(),
+        };
+        let table = match txn.open_table(self.table) {
+            Ok(table) => table,
+            Err(_) => return Vec::new(),
+        };
+        table
+            .range::<&[u8]>(range)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|(k, v)| (k.value().to_vec(), v.value().to_vec()))
+            .collect()
+    }
+}
@@ -0,0 +1,49 @@
+//! Test-only helper for running `sled` against a scratch directory.
+//!
+//! The ordered-iteration tests in [`super::utils`] used to hardcode
+//! `/tmp/...`, which doesn't exist (or isn't writable) in every CI sandbox.
+//! [`TempDir`] instead creates a uniquely-named directory under the
+//! platform's real temp dir for each test and removes it again on drop, so
+//! tests can't collide with each other or leak files across runs.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use sled::Db;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A scratch directory, unique per call to [`TempDir::new`], that is
+/// recursively removed once it is dropped.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new(prefix: &str) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!(
+            "tendermint_light_client_{}_{}_{}",
+            prefix,
+            std::process::id(),
+            id
+        ));
+
+        std::fs::create_dir_all(&path).unwrap();
+
+        Self(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Opens a fresh `sled::Db` rooted at this scratch directory.
+    pub(crate) fn open_sled(&self) -> Db {
+        sled::open(&self.0).unwrap()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).ok();
+    }
+}
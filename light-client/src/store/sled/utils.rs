@@ -5,30 +5,40 @@
 use serde::{de::DeserializeOwned, Serialize};
 use std::marker::PhantomData;
 
+use super::backend::{KvBackend, KvTree};
 use crate::errors::{Error, ErrorKind};
-use sled::{Db, Tree};
 
 /// Provides a view over the database for storing a single value at the given prefix.
-pub fn single<V>(db: &Db, prefix: impl Into<Vec<u8>>) -> SingleDb<V> {
+pub fn single<B, V>(db: &B, prefix: impl Into<Vec<u8>>) -> SingleDb<B::Tree, V>
+where
+    B: KvBackend,
+{
     SingleDb::new(db, prefix)
 }
 
 /// Provides a view over the database for storing key/value pairs at the given prefix.
-pub fn key_value<K, V>(db: &Db, prefix: impl Into<Vec<u8>>) -> KeyValueDb<K, V> {
+pub fn key_value<B, K, V>(db: &B, prefix: impl Into<Vec<u8>>) -> KeyValueDb<K, V, B::Tree>
+where
+    B: KvBackend,
+{
     KeyValueDb::new(db.open_tree(prefix.into()).unwrap())
 }
 
 /// Provides a view over the database for storing a single value at the given prefix.
-pub struct SingleDb<V>(KeyValueDb<(), V>);
+pub struct SingleDb<T, V>(KeyValueDb<(), V, T>);
 
-impl<V> SingleDb<V> {
-    pub fn new(db: &Db, prefix: impl Into<Vec<u8>>) -> Self {
+impl<T, V> SingleDb<T, V>
+where
+    T: KvTree,
+{
+    pub fn new<B: KvBackend<Tree = T>>(db: &B, prefix: impl Into<Vec<u8>>) -> Self {
         Self(KeyValueDb::new(db.open_tree(prefix.into()).unwrap()))
     }
 }
 
-impl<V> SingleDb<V>
+impl<T, V> SingleDb<T, V>
 where
+    T: KvTree,
     V: Serialize + DeserializeOwned,
 {
     pub fn get(&self) -> Result<Option<V>, Error> {
@@ -42,32 +52,36 @@ where
 
 /// Provides a view over the database for storing key/value pairs at the given prefix.
 #[derive(Clone, Debug)]
-pub struct KeyValueDb<K, V> {
-    tree: Tree,
+pub struct KeyValueDb<K, V, T> {
+    tree: T,
     marker: PhantomData<(K, V)>,
 }
 
-impl<K, V> KeyValueDb<K, V> {
-    pub fn new(tree: Tree) -> Self {
+impl<K, V, T> KeyValueDb<K, V, T> {
+    pub fn new(tree: T) -> Self {
         Self {
             tree,
             marker: PhantomData,
         }
     }
+
+    /// Gives access to the underlying tree, for backends (e.g. `sled`) that
+    /// support atomic operations spanning several trees at once.
+    pub(crate) fn tree(&self) -> &T {
+        &self.tree
+    }
 }
 
-impl<K, V> KeyValueDb<K, V>
+impl<K, V, T> KeyValueDb<K, V, T>
 where
     K: Serialize,
     V: Serialize + DeserializeOwned,
+    T: KvTree,
 {
     pub fn get(&self, key: &K) -> Result<Option<V>, Error> {
         let key_bytes = serde_cbor::to_vec(&key).map_err(|e| ErrorKind::Store.context(e))?;
 
-        let value_bytes = self
-            .tree
-            .get(key_bytes)
-            .map_err(|e| ErrorKind::Store.context(e))?;
+        let value_bytes = self.tree.get(&key_bytes)?;
 
         match value_bytes {
             Some(bytes) => {
@@ -82,70 +96,108 @@ where
     pub fn contains_key(&self, key: &K) -> Result<bool, Error> {
         let key_bytes = serde_cbor::to_vec(&key).map_err(|e| ErrorKind::Store.context(e))?;
 
-        let exists = self
-            .tree
-            .contains_key(key_bytes)
-            .map_err(|e| ErrorKind::Store.context(e))?;
-
-        Ok(exists)
+        self.tree.contains_key(&key_bytes)
     }
 
     pub fn insert(&self, key: &K, value: &V) -> Result<(), Error> {
         let key_bytes = serde_cbor::to_vec(&key).map_err(|e| ErrorKind::Store.context(e))?;
         let value_bytes = serde_cbor::to_vec(&value).map_err(|e| ErrorKind::Store.context(e))?;
 
-        self.tree
-            .insert(key_bytes, value_bytes)
-            .map(|_| ())
-            .map_err(|e| ErrorKind::Store.context(e))?;
-
-        Ok(())
+        self.tree.insert(&key_bytes, &value_bytes)
     }
 
     pub fn remove(&self, key: &K) -> Result<(), Error> {
         let key_bytes = serde_cbor::to_vec(&key).map_err(|e| ErrorKind::Store.context(e))?;
 
-        self.tree
-            .remove(key_bytes)
-            .map_err(|e| ErrorKind::Store.context(e))?;
-
-        Ok(())
+        self.tree.remove(&key_bytes)
     }
 
     pub fn iter(&self) -> impl DoubleEndedIterator<Item = V> {
         self.tree
             .iter()
-            .flatten()
             .map(|(_, v)| serde_cbor::from_slice(&v))
             .flatten()
     }
+
+    /// Like [`KeyValueDb::iter`], but also decodes the key of each entry.
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = (K, V)>
+    where
+        K: DeserializeOwned,
+    {
+        self.tree.iter().filter_map(|(k, v)| {
+            let key = serde_cbor::from_slice(&k).ok()?;
+            let value = serde_cbor::from_slice(&v).ok()?;
+            Some((key, value))
+        })
+    }
+
+    /// Like [`KeyValueDb::entries`], but only decodes entries whose key
+    /// sorts before `before`, using the tree's ordered `range` instead of
+    /// scanning every entry.
+    pub fn entries_before(&self, before: &K) -> impl DoubleEndedIterator<Item = (K, V)>
+    where
+        K: DeserializeOwned,
+    {
+        let end = serde_cbor::to_vec(before).unwrap_or_default();
+
+        self.tree.range(Vec::new(), end).filter_map(|(k, v)| {
+            let key = serde_cbor::from_slice(&k).ok()?;
+            let value = serde_cbor::from_slice(&v).ok()?;
+            Some((key, value))
+        })
+    }
 }
 
-// TODO: The test below is currently disabled because it fails on CI as we don't have
-// access to `/tmp`. Need to figure out how to specify a proper temp dir.
-
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//     use crate::types::Height;
-
-//     #[test]
-//     fn iter_next_back_returns_highest_height() {
-//         const DB_PATH: &str = "/tmp/tendermint_light_client_sled_test/";
-//         std::fs::remove_dir_all(DB_PATH).unwrap();
-//         let db = sled::open(DB_PATH).unwrap();
-//         let kv: KeyValueDb<Height, Height> = key_value("light_store/verified");
-
-//         kv.insert(&db, &1, &1).unwrap();
-//         kv.insert(&db, &589473798493, &589473798493).unwrap();
-//         kv.insert(&db, &12342425, &12342425).unwrap();
-//         kv.insert(&db, &4, &4).unwrap();
-
-//         let mut iter = kv.iter(&db);
-//         assert_eq!(iter.next_back(), Some(589473798493));
-//         assert_eq!(iter.next_back(), Some(12342425));
-//         assert_eq!(iter.next_back(), Some(4));
-//         assert_eq!(iter.next_back(), Some(1));
-//         assert_eq!(iter.next_back(), None);
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::TempDir;
+    use super::*;
+
+    fn kv(scratch: &TempDir) -> (sled::Db, KeyValueDb<u64, u64, sled::Tree>) {
+        let db = scratch.open_sled();
+        let tree: KeyValueDb<u64, u64, sled::Tree> = key_value(&db, "light_store/verified");
+        (db, tree)
+    }
+
+    #[test]
+    fn iter_next_back_returns_highest_height() {
+        let scratch = TempDir::new("iter_next_back");
+        let (_db, kv) = kv(&scratch);
+
+        for height in [1, 589_473_798_493, 12_342_425, 4] {
+            kv.insert(&height, &height).unwrap();
+        }
+
+        let mut iter = kv.iter();
+        assert_eq!(iter.next_back(), Some(589_473_798_493));
+        assert_eq!(iter.next_back(), Some(12_342_425));
+        assert_eq!(iter.next_back(), Some(4));
+        assert_eq!(iter.next_back(), Some(1));
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn get_and_remove_round_trip() {
+        let scratch = TempDir::new("get_remove");
+        let (_db, kv) = kv(&scratch);
+
+        kv.insert(&1, &42).unwrap();
+        assert_eq!(kv.get(&1).unwrap(), Some(42));
+
+        kv.remove(&1).unwrap();
+        assert_eq!(kv.get(&1).unwrap(), None);
+    }
+
+    #[test]
+    fn entries_decodes_both_key_and_value() {
+        let scratch = TempDir::new("entries");
+        let (_db, kv) = kv(&scratch);
+
+        kv.insert(&1, &10).unwrap();
+        kv.insert(&2, &20).unwrap();
+
+        let mut entries: Vec<(u64, u64)> = kv.entries().collect();
+        entries.sort();
+        assert_eq!(entries, vec![(1, 10), (2, 20)]);
+    }
+}
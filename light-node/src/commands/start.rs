@@ -16,7 +16,8 @@ use abscissa_core::FrameworkError;
 use abscissa_core::Options;
 use abscissa_core::Runnable;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::net::SocketAddr;
 use std::ops::Deref;
 use std::time::Duration;
@@ -30,10 +31,54 @@ use tendermint_light_client::fork_detector::ProdForkDetector;
 use tendermint_light_client::light_client;
 use tendermint_light_client::light_client::LightClient;
 use tendermint_light_client::peer_list::{PeerList, PeerListBuilder};
+use tendermint_light_client::slasher::Slasher;
 use tendermint_light_client::state::State;
+use tendermint_light_client::store::sled::AnyLightStore;
 use tendermint_light_client::supervisor::Handle;
 use tendermint_light_client::supervisor::{Instance, Supervisor};
-use tendermint_light_client::types::Status;
+use tendermint_light_client::types::{Height, PeerId, Status};
+
+use sled::Db;
+
+/// Storage engine backing the shared light store (the instance
+/// [`StartCmd::assert_init_was_run`] imports a trusted checkpoint into and
+/// [`StartCmd::prune_shared_store`] prunes).
+///
+/// Both variants are backed by the same [`KvBackend`](tendermint_light_client::store::sled::backend::KvBackend)
+/// abstraction. This only selects the shared store opened by
+/// [`StartCmd::open_shared_store`]: `prune`/`try_update`/checkpoint
+/// import-export are inherent methods on [`AnyLightStore`], not on the
+/// [`LightStore`](tendermint_light_client::store::LightStore) trait, so
+/// picking a backend at runtime needs a concretely-typed store rather than
+/// `light_store_factory()`'s opaque, hardwired return type. The per-peer
+/// stores `light_store_factory()` builds in
+/// [`StartCmd::make_instance`] are unaffected by this flag and keep using
+/// whatever backend the factory is configured for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreBackend {
+    Sled,
+    Redb,
+}
+
+/// How many of the most recently synced heights `run` keeps out of every
+/// `prune_shared_store` pass, as a stand-in for the in-flight heights each
+/// instance's `State::verification_trace` would otherwise provide.
+const RECENTLY_SYNCED_HEIGHTS_KEPT: usize = 8;
+
+impl std::str::FromStr for StoreBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sled" => Ok(StoreBackend::Sled),
+            "redb" => Ok(StoreBackend::Redb),
+            other => Err(format!(
+                "unknown store backend '{}', expected 'sled' or 'redb'",
+                other
+            )),
+        }
+    }
+}
 
 /// `start` subcommand
 #[derive(Command, Debug, Options)]
@@ -49,13 +94,59 @@ pub struct StartCmd {
     /// Path to configuration file
     #[options(short = "c", long = "config", help = "path to light_node.toml")]
     pub config: Option<PathBuf>,
+
+    /// Storage engine for the shared light store
+    #[options(
+        long = "store-backend",
+        help = "storage engine for the shared light store: sled (default) or redb"
+    )]
+    pub store_backend: Option<StoreBackend>,
+
+    /// Path to the shared light store
+    #[options(
+        long = "shared-store-path",
+        help = "directory (sled) or file (redb) the shared light store is opened at"
+    )]
+    pub shared_store_path: Option<PathBuf>,
+
+    /// Path to a portable trusted checkpoint to bootstrap the shared store from
+    #[options(
+        long = "trusted-checkpoint-file",
+        help = "checkpoint file (as written by the light store's `export_trusted_checkpoint`) to import if the shared store has no trusted or verified state yet"
+    )]
+    pub trusted_checkpoint_file: Option<PathBuf>,
+
+    /// Height window, relative to the latest synced height, retained in the
+    /// shared store. Omitting this disables pruning entirely.
+    #[options(
+        long = "store-retention-window",
+        help = "number of heights, relative to the latest synced height, to retain in the shared store (omit to disable pruning)"
+    )]
+    pub store_retention_window: Option<u64>,
+
+    /// Every `store_retention_keep_every`-th height is kept regardless of
+    /// the retention window, so the store retains periodic checkpoints.
+    #[options(
+        long = "store-retention-keep-every",
+        help = "retain every Nth height regardless of the retention window (default: 1000)"
+    )]
+    pub store_retention_keep_every: Option<u64>,
+
+    /// Path to the slasher's persistent equivocation index. Kept separate
+    /// from the shared light store: the two are pruned on different
+    /// horizons, and there's no reason to couple their lifetimes.
+    #[options(
+        long = "slasher-store-path",
+        help = "directory the equivocation-detection index is kept in (default: slasher_store)"
+    )]
+    pub slasher_store_path: Option<PathBuf>,
 }
 
 impl Runnable for StartCmd {
     /// Start the application.
     fn run(&self) {
         if let Err(err) = abscissa_tokio::run(&APPLICATION, async {
-            StartCmd::assert_init_was_run();
+            self.assert_init_was_run();
             let mut supervisor = self.construct_supervisor();
 
             let rpc_handler = supervisor.handle();
@@ -64,15 +155,52 @@ impl Runnable for StartCmd {
             let handle = supervisor.handle();
             std::thread::spawn(|| supervisor.run());
 
+            // Heights synced recently enough that a bisection started
+            // against one of them (tracked per-instance in each
+            // `State::verification_trace`, which this command doesn't have
+            // direct access to) might still be in flight. `prune_shared_store`
+            // never drops anything in this set, so a pruning pass can't
+            // remove a height the supervisor is actively verifying against.
+            let mut recently_synced_heights: Vec<Height> = Vec::new();
+
+            // Fed one verified commit at a time below, so a validator that
+            // double-signs across two blocks this node verifies (even
+            // across separate runs, since the index is persistent) is
+            // still caught and reported.
+            let mut slasher = self.open_slasher();
+            let (slasher_reporter, slasher_peer) = self.slasher_inputs();
+
             loop {
                 match handle.verify_to_highest() {
                     Ok(light_block) => {
                         status_info!("synced to block {}", light_block.height().to_string());
+
+                        if let Some(peer) = slasher_peer {
+                            if let Err(err) =
+                                slasher.observe_commit(&light_block, &slasher_reporter, peer)
+                            {
+                                status_err!(
+                                    "slasher failed to index commit at height {}: {}",
+                                    light_block.height(),
+                                    err
+                                );
+                            }
+                        }
+
+                        recently_synced_heights.push(light_block.height());
+                        if recently_synced_heights.len() > RECENTLY_SYNCED_HEIGHTS_KEPT {
+                            recently_synced_heights.remove(0);
+                        }
+
+                        let keep_heights: HashSet<Height> =
+                            recently_synced_heights.iter().copied().collect();
+                        self.prune_shared_store(light_block.height(), &keep_heights);
                     }
                     Err(err) => {
                         status_err!("sync failed: {}", err);
                     }
                 }
+
                 // TODO(liamsi): use ticks and make this configurable:
                 std::thread::sleep(Duration::from_millis(800));
             }
@@ -100,16 +228,129 @@ impl config::Override<LightNodeConfig> for StartCmd {
     }
 }
 impl StartCmd {
-    fn assert_init_was_run() {
+    /// Opens the shared light store (the one `assert_init_was_run` imports
+    /// a trusted checkpoint into, `prune_shared_store` prunes, and
+    /// `construct_supervisor` seeds with the primary's trusted blocks) at
+    /// `self.shared_store_path`, using `self.store_backend` (defaulting to
+    /// `sled`).
+    ///
+    /// This deliberately bypasses `light_store_factory()`: that factory's
+    /// return type is fixed at whichever backend it's built for, which
+    /// can't honor a CLI flag chosen at runtime, and it doesn't expose the
+    /// [`AnyLightStore`] inherent methods this command needs.
+    fn open_shared_store(&self) -> AnyLightStore {
+        let path = self
+            .shared_store_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("light_store"));
+
+        let opened = match self.store_backend.unwrap_or(StoreBackend::Sled) {
+            StoreBackend::Sled => AnyLightStore::open_sled(&path),
+            StoreBackend::Redb => AnyLightStore::open_redb(&path),
+        };
+
+        opened.unwrap_or_else(|err| {
+            status_err!(
+                "failed to open shared light store at {}: {}",
+                path.display(),
+                err
+            );
+            process::exit(1);
+        })
+    }
+
+    fn assert_init_was_run(&self) {
         // TODO(liamsi): handle errors properly:
 
-        let shared_store = app_reader().light_store_factory().create(&app_config().shared_state_config);
+        let mut shared_store = self.open_shared_store();
 
         if shared_store.latest_trusted_or_verified().is_none() {
+            // Fall back to a portable trusted checkpoint, if one is
+            // configured, so a fresh node can bootstrap without having to
+            // go through the `initialize` subcommand against a live RPC
+            // endpoint. `import_trusted_checkpoint` checks the checkpoint's
+            // header and commit against its own embedded validator set only;
+            // it does not check that validator set against any other source.
+            let imported = self
+                .trusted_checkpoint_file
+                .as_ref()
+                .map(|path| shared_store.import_trusted_checkpoint(path));
+
+            match imported {
+                Some(Ok(())) => return,
+                Some(Err(err)) => {
+                    status_err!("failed to import trusted checkpoint: {}", err);
+                }
+                None => {}
+            }
+
             status_err!("no trusted or verified state in store for primary, please initialize with the `initialize` subcommand first");
             std::process::exit(1);
         }
     }
+
+    /// Drops light blocks in the shared store below the configured
+    /// retention window, other than those in `keep_heights`.
+    ///
+    /// `supervisor.run()` owns the store instance the primary/witnesses
+    /// actually verify against on its own thread, so this opens a second
+    /// handle onto the same backing store (the same pattern already used
+    /// by [`StartCmd::construct_supervisor`] and [`StartCmd::make_instance`]
+    /// to obtain independent store handles) rather than threading a prune
+    /// call through [`Handle`], which doesn't expose it.
+    ///
+    /// Pruning is disabled unless `--store-retention-window` is given: an
+    /// operator who hasn't opted in shouldn't have their shared store
+    /// silently start losing history.
+    fn prune_shared_store(&self, latest_height: Height, keep_heights: &HashSet<Height>) {
+        let window = match self.store_retention_window {
+            Some(window) => window,
+            None => return,
+        };
+        let keep_every = self.store_retention_keep_every.unwrap_or(1000);
+
+        let before = match Height::try_from(latest_height.value().saturating_sub(window)) {
+            Ok(height) => height,
+            Err(_) => return,
+        };
+
+        let mut shared_store = self.open_shared_store();
+        shared_store.prune(before, keep_every, keep_heights);
+    }
+
+    /// Opens the `sled` database backing the [`Slasher`] at
+    /// `self.slasher_store_path` (defaulting to `slasher_store`).
+    fn open_slasher(&self) -> Slasher<Db> {
+        let path = self
+            .slasher_store_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("slasher_store"));
+
+        let db = sled::open(&path).unwrap_or_else(|err| {
+            status_err!(
+                "failed to open slasher store at {}: {}",
+                path.display(),
+                err
+            );
+            process::exit(1);
+        });
+
+        Slasher::new(&db)
+    }
+
+    /// Builds the evidence reporter and primary peer id [`Slasher::observe_commit`]
+    /// needs, the same way [`StartCmd::construct_supervisor`] builds its own
+    /// `ProdEvidenceReporter` from the configured light clients.
+    fn slasher_inputs(&self) -> (ProdEvidenceReporter, Option<PeerId>) {
+        let mut peer_map = HashMap::new();
+        for light_conf in &app_config().light_clients {
+            peer_map.insert(light_conf.peer_id, light_conf.address.clone());
+        }
+        let primary = app_config().light_clients.first().map(|c| c.peer_id);
+
+        (ProdEvidenceReporter::new(peer_map), primary)
+    }
+
     // TODO: this should do proper error handling, be gerneralized
     // then moved to to the light-client crate.
     fn make_instance(
@@ -174,7 +415,7 @@ impl StartCmd {
         }
         let peer_list = peer_list.build();
 
-        let mut shared_state = app_reader().light_store_factory().create(&conf.shared_state_config);
+        let mut shared_state = self.open_shared_store();
 
         peer_list
             .primary()